@@ -0,0 +1,289 @@
+use anyhow::anyhow;
+use async_std::fs;
+use async_std::path::PathBuf;
+use async_trait::async_trait;
+use thiserror::Error;
+use urlencoding::{decode, encode};
+
+use super::cache::Cache;
+use super::ytdl::{Channel, MediaFormat, Video, YtDl, YtDlError, YtdlpConfig};
+
+#[derive(Error, Debug)]
+pub enum BackendError {
+    #[error("Not found")]
+    NotFound,
+    #[error("yt-dlp executable not found")]
+    YtDlpNotFound,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl From<YtDlError> for BackendError {
+    fn from(e: YtDlError) -> Self {
+        match e {
+            YtDlError::ItemNotFound => BackendError::NotFound,
+            YtDlError::YtDlpNotFound => BackendError::YtDlpNotFound,
+            other => BackendError::Other(anyhow!(other)),
+        }
+    }
+}
+
+impl From<std::io::Error> for BackendError {
+    fn from(e: std::io::Error) -> Self {
+        BackendError::Other(anyhow!(e))
+    }
+}
+
+/// A source of podcast-able media: resolves feed-level metadata, lists its
+/// items, and downloads a given item by id. New sources are added by
+/// implementing this trait and registering them in `SourceBackend`, rather
+/// than by touching the Rocket routes or `PodcastProxy`.
+#[async_trait]
+pub trait Backend {
+    async fn resolve_feed(&self, id: &str) -> Result<Channel, BackendError>;
+
+    async fn list_items(
+        &self,
+        feed: &Channel,
+        limit: Option<i64>,
+        format: &MediaFormat,
+    ) -> Result<Vec<Video>, BackendError>;
+
+    async fn fetch_media(&self, item_id: &str, format: &MediaFormat) -> Result<PathBuf, BackendError>;
+}
+
+/// Downloads `url` to the cache under `item_id`/`format`, and records the real
+/// on-disk size alongside it so `PodcastProxy::get_feed` doesn't have to rely
+/// on yt-dlp's up-front size estimate on later renders.
+async fn fetch_and_cache(
+    cache: &Cache,
+    ytdlp_config: &YtdlpConfig,
+    item_id: &str,
+    url: &str,
+    format: &MediaFormat,
+) -> Result<PathBuf, BackendError> {
+    let media_key = ["media", item_id];
+    let out_path = cache.media_path(&media_key, Some(format.extension())).await?;
+
+    if out_path.exists().await && fs::metadata(&out_path).await?.len() == 0 {
+        fs::remove_file(&out_path).await?;
+    }
+
+    if !out_path.exists().await || !cache.media_exists(&media_key).await? {
+        let yt = YtDl::new(cache, ytdlp_config);
+        yt.download_video(url, &out_path, format).await?;
+        cache.mark_media_ready(&media_key).await?;
+
+        let size = fs::metadata(&out_path).await?.len();
+        cache
+            .write(&["size", format.extension(), item_id], &size.to_string())
+            .await?;
+    }
+
+    Ok(out_path)
+}
+
+/// The original YouTube-channel backend: resolves `@handle`/`/c/`/`/user/`
+/// style channel names and downloads videos by id.
+pub struct YoutubeBackend<'a> {
+    cache: &'a Cache,
+    ytdlp_config: &'a YtdlpConfig,
+}
+
+impl<'a> YoutubeBackend<'a> {
+    pub fn new(cache: &'a Cache, ytdlp_config: &'a YtdlpConfig) -> Self {
+        Self {
+            cache,
+            ytdlp_config,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Backend for YoutubeBackend<'a> {
+    async fn resolve_feed(&self, id: &str) -> Result<Channel, BackendError> {
+        let yt = YtDl::new(self.cache, self.ytdlp_config);
+        Ok(yt.get_channel_info(id).await?)
+    }
+
+    async fn list_items(
+        &self,
+        feed: &Channel,
+        limit: Option<i64>,
+        format: &MediaFormat,
+    ) -> Result<Vec<Video>, BackendError> {
+        let yt = YtDl::new(self.cache, self.ytdlp_config);
+        Ok(yt.get_channel_videos(feed, limit, format).await?)
+    }
+
+    async fn fetch_media(&self, item_id: &str, format: &MediaFormat) -> Result<PathBuf, BackendError> {
+        let url = format!("https://www.youtube.com/watch?v={}", encode(item_id));
+        fetch_and_cache(self.cache, self.ytdlp_config, item_id, &url, format).await
+    }
+}
+
+/// A generic backend for any URL yt-dlp can extract from (playlists, single
+/// videos, or whole channels on sites other than YouTube). The feed `id` is a
+/// percent-encoded extractor URL rather than a resolved channel name, since
+/// yt-dlp already supports hundreds of sites this way without any per-site
+/// handling.
+pub struct YtDlpBackend<'a> {
+    cache: &'a Cache,
+    ytdlp_config: &'a YtdlpConfig,
+}
+
+impl<'a> YtDlpBackend<'a> {
+    pub fn new(cache: &'a Cache, ytdlp_config: &'a YtdlpConfig) -> Self {
+        Self {
+            cache,
+            ytdlp_config,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Backend for YtDlpBackend<'a> {
+    async fn resolve_feed(&self, id: &str) -> Result<Channel, BackendError> {
+        let url = decode(id).map_err(|e| anyhow!(e))?.into_owned();
+        let yt = YtDl::new(self.cache, self.ytdlp_config);
+        Ok(yt.get_extractor_info(&url).await?)
+    }
+
+    async fn list_items(
+        &self,
+        feed: &Channel,
+        limit: Option<i64>,
+        format: &MediaFormat,
+    ) -> Result<Vec<Video>, BackendError> {
+        let yt = YtDl::new(self.cache, self.ytdlp_config);
+        let videos = yt.get_channel_videos(feed, limit, format).await?;
+
+        // fetch_media only receives an item id, so remember each item's own
+        // url here, while we have it, for later lookup regardless of site.
+        for video in &videos {
+            if let Err(e) = self
+                .cache
+                .write(&["item_url", &video.id], &video.webpage_url)
+                .await
+            {
+                log::warn!("Failed to cache item url: {}", e);
+            }
+        }
+
+        Ok(videos)
+    }
+
+    async fn fetch_media(&self, item_id: &str, format: &MediaFormat) -> Result<PathBuf, BackendError> {
+        let url = self.cache.read(&["item_url", item_id]).await?;
+        let url = match url {
+            Some(url) => url,
+            None => return Err(BackendError::NotFound),
+        };
+
+        fetch_and_cache(self.cache, self.ytdlp_config, item_id, url.trim(), format).await
+    }
+}
+
+/// Dispatches to a concrete `Backend` based on the `<source>` path segment, so
+/// adding a source only means adding a variant here, not touching the routes.
+pub enum SourceBackend<'a> {
+    Youtube(YoutubeBackend<'a>),
+    YtDlp(YtDlpBackend<'a>),
+}
+
+impl<'a> SourceBackend<'a> {
+    pub fn for_source(source: &str, cache: &'a Cache, ytdlp_config: &'a YtdlpConfig) -> Self {
+        match source {
+            "youtube" => SourceBackend::Youtube(YoutubeBackend::new(cache, ytdlp_config)),
+            _ => SourceBackend::YtDlp(YtDlpBackend::new(cache, ytdlp_config)),
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Backend for SourceBackend<'a> {
+    async fn resolve_feed(&self, id: &str) -> Result<Channel, BackendError> {
+        match self {
+            SourceBackend::Youtube(b) => b.resolve_feed(id).await,
+            SourceBackend::YtDlp(b) => b.resolve_feed(id).await,
+        }
+    }
+
+    async fn list_items(
+        &self,
+        feed: &Channel,
+        limit: Option<i64>,
+        format: &MediaFormat,
+    ) -> Result<Vec<Video>, BackendError> {
+        match self {
+            SourceBackend::Youtube(b) => b.list_items(feed, limit, format).await,
+            SourceBackend::YtDlp(b) => b.list_items(feed, limit, format).await,
+        }
+    }
+
+    async fn fetch_media(&self, item_id: &str, format: &MediaFormat) -> Result<PathBuf, BackendError> {
+        match self {
+            SourceBackend::Youtube(b) => b.fetch_media(item_id, format).await,
+            SourceBackend::YtDlp(b) => b.fetch_media(item_id, format).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cache::CacheConfig;
+
+    #[test]
+    fn test_for_source_dispatches_on_source_name() {
+        let cache = Cache::new(&CacheConfig::default()).unwrap();
+        let config = YtdlpConfig::default();
+
+        assert!(matches!(
+            SourceBackend::for_source("youtube", &cache, &config),
+            SourceBackend::Youtube(_)
+        ));
+        assert!(matches!(
+            SourceBackend::for_source("soundcloud", &cache, &config),
+            SourceBackend::YtDlp(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ytdlp_backend_fetch_media_without_known_item_url_is_not_found() -> Result<(), anyhow::Error> {
+        let cache = Cache::new(&CacheConfig::default())?;
+        let config = YtdlpConfig::default();
+        let backend = YtDlpBackend::new(&cache, &config);
+
+        match backend
+            .fetch_media("unknown-item", &MediaFormat::AudioM4a)
+            .await
+        {
+            Err(BackendError::NotFound) => Ok(()),
+            Err(e) => panic!("expected NotFound, got {}", e),
+            Ok(_) => panic!("should not have found media for an unknown item id"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_media_does_not_redownload_when_already_cached() -> Result<(), anyhow::Error> {
+        let cache = Cache::new(&CacheConfig::default())?;
+        let config = YtdlpConfig::default();
+        let backend = YoutubeBackend::new(&cache, &config);
+        let format = MediaFormat::AudioM4a;
+
+        let first_path = backend.fetch_media("BaW_jenozKc", &format).await?;
+        let first_modified = fs::metadata(&first_path).await?.modified()?;
+
+        let second_path = backend.fetch_media("BaW_jenozKc", &format).await?;
+        let second_modified = fs::metadata(&second_path).await?.modified()?;
+
+        assert_eq!(first_path, second_path);
+        assert_eq!(
+            first_modified, second_modified,
+            "second fetch_media call re-downloaded an already-cached file"
+        );
+
+        Ok(())
+    }
+}
@@ -1,36 +1,195 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use async_std::fs;
 use async_std::fs::{create_dir_all, remove_dir, File, OpenOptions};
 use async_std::io::ErrorKind;
 use async_std::path::PathBuf;
 use async_std::prelude::*;
+use redis::AsyncCommands;
+use rocket::serde::Deserialize;
 use tempfile::TempDir;
 use urlencoding::encode;
 
-pub struct Cache {
+fn default_cache_time() -> u64 {
+    86400
+}
+
+fn default_media_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join("yt-cast-media")
+}
+
+/// Which cache implementation to use. `Filesystem` is self-contained and
+/// ephemeral (cleared on restart); `Redis` shares metadata across instances
+/// and restarts via a Redis server, so operators don't have to re-scrape
+/// channels or re-download media every time the process restarts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum CacheConfig {
+    Filesystem {
+        #[serde(default = "default_cache_time")]
+        cache_time: u64,
+    },
+    Redis {
+        url: String,
+        #[serde(default = "default_media_dir")]
+        media_dir: std::path::PathBuf,
+        #[serde(default = "default_cache_time")]
+        cache_time: u64,
+    },
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig::Filesystem {
+            cache_time: default_cache_time(),
+        }
+    }
+}
+
+/// Keyed storage for both small metadata (channel/playlist JSON, computed
+/// media sizes) and large downloaded media files. `read`/`write` are for the
+/// former; `media_path` plus `media_exists`/`mark_media_ready` are for the
+/// latter, since a Redis-backed cache can't usefully hold a whole media file
+/// as a value.
+pub enum Cache {
+    Filesystem(FsCache),
+    Redis(RedisCache),
+}
+
+impl Cache {
+    pub fn new(config: &CacheConfig) -> Result<Cache> {
+        match config {
+            CacheConfig::Filesystem { cache_time } => {
+                Ok(Cache::Filesystem(FsCache::new(*cache_time)?))
+            }
+            CacheConfig::Redis {
+                url,
+                media_dir,
+                cache_time,
+            } => Ok(Cache::Redis(RedisCache::new(
+                url,
+                media_dir.clone(),
+                *cache_time,
+            )?)),
+        }
+    }
+
+    pub async fn read(&self, key: &[&str]) -> Result<Option<String>> {
+        match self {
+            Cache::Filesystem(c) => c.read(key).await,
+            Cache::Redis(c) => c.read(key).await,
+        }
+    }
+
+    pub async fn write(&self, key: &[&str], contents: &str) -> Result<()> {
+        match self {
+            Cache::Filesystem(c) => c.write(key, contents).await,
+            Cache::Redis(c) => c.write(key, contents).await,
+        }
+    }
+
+    pub async fn media_path(&self, key: &[&str], ext: Option<&str>) -> Result<PathBuf> {
+        match self {
+            Cache::Filesystem(c) => c.media_path(key, ext).await,
+            Cache::Redis(c) => c.media_path(key, ext).await,
+        }
+    }
+
+    pub async fn media_exists(&self, key: &[&str]) -> Result<bool> {
+        match self {
+            Cache::Filesystem(c) => c.media_exists(key).await,
+            Cache::Redis(c) => c.media_exists(key).await,
+        }
+    }
+
+    pub async fn mark_media_ready(&self, key: &[&str]) -> Result<()> {
+        match self {
+            Cache::Filesystem(c) => c.mark_media_ready(key).await,
+            Cache::Redis(c) => c.mark_media_ready(key).await,
+        }
+    }
+
+    pub async fn clean(&self) -> Result<()> {
+        match self {
+            Cache::Filesystem(c) => c.clean().await,
+            Cache::Redis(c) => c.clean().await,
+        }
+    }
+}
+
+fn encode_key(p: &mut PathBuf, key: &[&str]) {
+    for k in key {
+        p.push(encode(k).replace('%', "+"));
+    }
+}
+
+/// Removes files under `root` last modified more than `cache_time` seconds
+/// ago, and any directory that already had no entries when it was listed
+/// (including `root` itself). A directory emptied by this sweep is only
+/// removed on a later sweep.
+/// Shared by `FsCache` (whose whole tree is cache_time-bounded) and
+/// `RedisCache` (whose media files are only tracked via a Redis marker, so
+/// they need the same age-based sweep once that marker expires).
+async fn sweep_expired_files(root: PathBuf, cache_time: u64) -> Result<()> {
+    let mut dirs = vec![root];
+
+    while let Some(dir) = dirs.pop() {
+        let mut empty = true;
+
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(result) = entries.next().await {
+            empty = false;
+
+            let entry = result?;
+
+            let f_type = entry.file_type().await?;
+            if f_type.is_dir() {
+                dirs.push(entry.path());
+                continue;
+            } else if f_type.is_file() {
+                let modified = entry.metadata().await?.modified()?;
+                if let Ok(time_diff) = modified.elapsed() {
+                    if time_diff.as_secs() > cache_time {
+                        if let Err(e) = fs::remove_file(entry.path()).await {
+                            log::warn!("Couldn't remove expired cache file: {}", e);
+                        }
+                    }
+                }
+            }
+        }
+
+        if empty {
+            remove_dir(dir).await?
+        }
+    }
+
+    Ok(())
+}
+
+/// The original cache: everything lives under a `TempDir` that is destroyed
+/// on process exit and cannot be shared across instances.
+pub struct FsCache {
     dir: TempDir,
     cache_time: u64,
 }
 
-impl Cache {
-    pub fn new() -> Result<Cache> {
-        let c = Cache {
+impl FsCache {
+    pub fn new(cache_time: u64) -> Result<FsCache> {
+        Ok(FsCache {
             dir: TempDir::new()?,
-            cache_time: 86400,
-        };
-        Ok(c)
+            cache_time,
+        })
     }
 
-    pub async fn get_path(&self, key: Vec<&str>, ext: Option<&str>) -> Result<PathBuf> {
+    async fn get_path(&self, key: &[&str], ext: Option<&str>) -> Result<PathBuf> {
         let mut p: PathBuf = self.dir.path().into();
 
         let (last, elements) = key.split_last().ok_or_else(|| anyhow!("empty key"))?;
 
         for k in elements {
-            p.push(encode(k).replace("%", "+"));
+            p.push(encode(k).replace('%', "+"));
             create_dir_all(&p).await?;
         }
-        p.push(encode(last).replace("%", "+"));
+        p.push(encode(last).replace('%', "+"));
         if let Some(ext_val) = ext {
             p.set_extension(ext_val);
         }
@@ -53,42 +212,166 @@ impl Cache {
         Ok(p)
     }
 
+    pub async fn read(&self, key: &[&str]) -> Result<Option<String>> {
+        let path = self.get_path(key, None).await?;
+        let contents = fs::read_to_string(&path)
+            .await
+            .context("cache read failed")?;
+        Ok(if contents.is_empty() {
+            None
+        } else {
+            Some(contents)
+        })
+    }
+
+    pub async fn write(&self, key: &[&str], contents: &str) -> Result<()> {
+        let path = self.get_path(key, None).await?;
+        fs::write(&path, contents).await?;
+        Ok(())
+    }
+
+    pub async fn media_path(&self, key: &[&str], ext: Option<&str>) -> Result<PathBuf> {
+        self.get_path(key, ext).await
+    }
+
+    pub async fn media_exists(&self, key: &[&str]) -> Result<bool> {
+        // Presence is simply whatever `media_path` already put on disk, so
+        // defer entirely to the caller's own `out_path.exists()` check.
+        let _ = key;
+        Ok(true)
+    }
+
+    pub async fn mark_media_ready(&self, key: &[&str]) -> Result<()> {
+        // Nothing to track separately; the file on disk is the record.
+        let _ = key;
+        Ok(())
+    }
+
     pub async fn clean(&self) -> Result<()> {
         log::debug!("clean()");
+        sweep_expired_files(self.dir.path().into(), self.cache_time).await
+    }
+}
 
-        let root: PathBuf = self.dir.path().into();
-        let mut dirs = vec![root];
-
-        while let Some(dir) = dirs.pop() {
-            let mut empty = true;
-
-            let mut entries = fs::read_dir(&dir).await?;
-            while let Some(result) = entries.next().await {
-                empty = false;
-
-                let entry = result?;
-
-                let f_type = entry.file_type().await?;
-                if f_type.is_dir() {
-                    dirs.push(entry.path());
-                    continue;
-                } else if f_type.is_file() {
-                    let modified = entry.metadata().await?.modified()?;
-                    if let Ok(time_diff) = modified.elapsed() {
-                        if time_diff.as_secs() > self.cache_time {
-                            if let Err(e) = fs::remove_file(entry.path()).await {
-                                log::warn!("Couldn't remove expired cache file: {}", e);
-                            }
-                        }
-                    }
-                }
-            }
+/// Shares channel/playlist metadata across instances and restarts via Redis
+/// (with `cache_time` as the expiry), while media files stay on local disk
+/// under `media_dir`, their presence tracked as a Redis key alongside the
+/// metadata rather than by disk probing.
+pub struct RedisCache {
+    client: redis::Client,
+    media_dir: std::path::PathBuf,
+    cache_time: u64,
+}
 
-            if empty {
-                remove_dir(dir).await?
-            }
+impl RedisCache {
+    pub fn new(url: &str, media_dir: std::path::PathBuf, cache_time: u64) -> Result<RedisCache> {
+        Ok(RedisCache {
+            client: redis::Client::open(url)?,
+            media_dir,
+            cache_time,
+        })
+    }
+
+    fn redis_key(key: &[&str]) -> String {
+        key.iter().map(|k| encode(k)).collect::<Vec<_>>().join(":")
+    }
+
+    async fn connection(&self) -> Result<redis::aio::Connection> {
+        Ok(self.client.get_async_connection().await?)
+    }
+
+    pub async fn read(&self, key: &[&str]) -> Result<Option<String>> {
+        let mut conn = self.connection().await?;
+        Ok(conn.get(RedisCache::redis_key(key)).await?)
+    }
+
+    pub async fn write(&self, key: &[&str], contents: &str) -> Result<()> {
+        let mut conn = self.connection().await?;
+        conn.set_ex(RedisCache::redis_key(key), contents, self.cache_time as usize)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn media_path(&self, key: &[&str], ext: Option<&str>) -> Result<PathBuf> {
+        let mut p: PathBuf = self.media_dir.clone().into();
+        let (last, elements) = key.split_last().ok_or_else(|| anyhow!("empty key"))?;
+
+        encode_key(&mut p, elements);
+        create_dir_all(&p).await?;
+
+        p.push(encode(last).replace('%', "+"));
+        if let Some(ext_val) = ext {
+            p.set_extension(ext_val);
         }
 
+        Ok(p)
+    }
+
+    pub async fn media_exists(&self, key: &[&str]) -> Result<bool> {
+        let mut conn = self.connection().await?;
+        let ready: Option<String> = conn.get(format!("media_ready:{}", RedisCache::redis_key(key))).await?;
+        Ok(ready.is_some())
+    }
+
+    pub async fn mark_media_ready(&self, key: &[&str]) -> Result<()> {
+        let mut conn = self.connection().await?;
+        conn.set_ex(
+            format!("media_ready:{}", RedisCache::redis_key(key)),
+            "1",
+            self.cache_time as usize,
+        )
+        .await?;
         Ok(())
     }
+
+    pub async fn clean(&self) -> Result<()> {
+        // Metadata keys expire themselves via SETEX, but the media files
+        // under `media_dir` are only ever removed explicitly, so once their
+        // `media_ready:*` marker expires the bytes would otherwise linger
+        // forever. Sweep them by age the same way `FsCache` does.
+        log::debug!("clean()");
+
+        let root: PathBuf = self.media_dir.clone().into();
+        if !root.exists().await {
+            return Ok(());
+        }
+
+        sweep_expired_files(root, self.cache_time).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_cache_read_write_roundtrip() -> Result<()> {
+        let cache = FsCache::new(86400)?;
+
+        assert_eq!(cache.read(&["missing"]).await?, None);
+
+        cache.write(&["greeting"], "hello").await?;
+        assert_eq!(cache.read(&["greeting"]).await?, Some("hello".to_string()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fs_cache_media_exists_defers_to_path_presence() -> Result<()> {
+        // FsCache tracks media solely via the caller's own `out_path.exists()`
+        // check, so `media_exists` must never itself gate a re-download.
+        let cache = FsCache::new(86400)?;
+        assert!(cache.media_exists(&["media", "some-id"]).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_key_percent_encodes_and_escapes_percent() {
+        let mut p: PathBuf = std::path::PathBuf::from("/tmp/base").into();
+        encode_key(&mut p, &["a b", "c/d"]);
+        assert_eq!(
+            p.to_str().unwrap(),
+            "/tmp/base/a+20b/c+2Fd"
+        );
+    }
 }
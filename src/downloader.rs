@@ -0,0 +1,79 @@
+//! Fetches a fresh `yt-dlp` binary from its GitHub releases when the
+//! configured executable can't be found, or when `auto_update` is set.
+//! Gated behind the `downloader` feature since most deployments manage the
+//! binary themselves and would rather not take a dependency on GitHub.
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use async_process::Command;
+use async_std::fs;
+use async_std::io::ErrorKind;
+
+use super::ytdl::YtdlpConfig;
+
+const RELEASE_BASE_URL: &str = "https://github.com/yt-dlp/yt-dlp/releases/latest/download";
+
+/// The asset name yt-dlp publishes for the current platform on its releases
+/// page (https://github.com/yt-dlp/yt-dlp/releases).
+fn asset_name() -> Result<&'static str> {
+    match std::env::consts::OS {
+        "linux" => Ok("yt-dlp_linux"),
+        "macos" => Ok("yt-dlp_macos"),
+        "windows" => Ok("yt-dlp.exe"),
+        other => Err(anyhow!("no yt-dlp release asset for platform {}", other)),
+    }
+}
+
+/// Downloads the latest yt-dlp release binary for the current platform into
+/// `data_dir`, making it executable, and returns its path.
+async fn download_latest(data_dir: &Path) -> Result<PathBuf> {
+    let asset = asset_name()?;
+    let url = format!("{}/{}", RELEASE_BASE_URL, asset);
+
+    log::info!("Downloading yt-dlp from {}", url);
+
+    let bytes = reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("failed to fetch latest yt-dlp release")?
+        .bytes()
+        .await
+        .context("failed to read yt-dlp release body")?;
+
+    fs::create_dir_all(data_dir).await?;
+    let dest = data_dir.join(asset);
+    fs::write(&dest, &bytes).await?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(&dest).await?.permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&dest, perms).await?;
+    }
+
+    Ok(dest)
+}
+
+/// Whether `executable_path` is found when launched, regardless of whether
+/// it behaves like yt-dlp. Only a not-found error counts as missing; any
+/// other launch error (e.g. permission denied) counts as runnable.
+async fn is_runnable(executable_path: &str) -> bool {
+    match Command::new(executable_path).arg("--version").output().await {
+        Ok(_) => true,
+        Err(e) => e.kind() != ErrorKind::NotFound,
+    }
+}
+
+/// Ensures `config.executable_path` points at a working yt-dlp binary,
+/// downloading the latest release into `data_dir` when the configured
+/// binary can't be found, or unconditionally when `config.auto_update` is
+/// set. Leaves `config` untouched if neither applies.
+pub async fn ensure_yt_dlp(config: &mut YtdlpConfig, data_dir: &Path) -> Result<()> {
+    if config.auto_update || !is_runnable(&config.executable_path).await {
+        let path = download_latest(data_dir).await?;
+        config.executable_path = path.to_string_lossy().into_owned();
+    }
+
+    Ok(())
+}
@@ -9,31 +9,44 @@ use rocket::response::content::Xml;
 use rocket::serde::Deserialize;
 use rocket::State;
 
+mod backend;
 mod cache;
+#[cfg(feature = "downloader")]
+mod downloader;
 mod podcast_proxy;
 mod ytdl;
 
-use cache::Cache;
+use cache::{Cache, CacheConfig};
 use podcast_proxy::{PodcastError, PodcastProxy};
+use ytdl::{MediaFormat, YtdlpConfig};
 
 #[derive(Deserialize)]
 struct AppConfig {
     base_url: String,
     channel_whitelist: Vec<String>,
+    media_format: MediaFormat,
+    #[serde(default)]
+    ytdlp: YtdlpConfig,
+    #[serde(default)]
+    cache: CacheConfig,
 }
 
 struct AppState {
     proxy: PodcastProxy,
 }
 
-#[get("/feed/<channel_name>?<delay>")]
+#[get("/feed/<source>/<id>?<delay>")]
 async fn get_feed(
     config: &State<AppConfig>,
     state: &State<AppState>,
-    channel_name: &str,
+    source: &str,
+    id: &str,
     delay: Option<&str>
 ) -> Result<Xml<String>, Status> {
-    if !config.channel_whitelist.contains(&channel_name.to_string()) {
+    if !config
+        .channel_whitelist
+        .contains(&format!("{}/{}", source, id))
+    {
         return Err(Status::NotFound);
     }
 
@@ -41,7 +54,13 @@ async fn get_feed(
 
     match state
         .proxy
-        .get_feed(&format!("{}/media/", config.base_url), channel_name, delay_days)
+        .get_feed(
+            &format!("{}/media/{}/", config.base_url, source),
+            source,
+            id,
+            delay_days,
+            &config.media_format,
+        )
         .await
     {
         Ok(s) => Ok(Xml(s)),
@@ -55,12 +74,21 @@ async fn get_feed(
     }
 }
 
-#[get("/media/<id>")]
-async fn get_media(state: &State<AppState>, id: &str) -> Result<NamedFile, Status> {
-    let downloaded_path = state.proxy.get_video(id).await.map_err(|e| match e {
-        PodcastError::NotFound => Status::NotFound,
-        _ => Status::InternalServerError,
-    })?;
+#[get("/media/<source>/<id>")]
+async fn get_media(
+    config: &State<AppConfig>,
+    state: &State<AppState>,
+    source: &str,
+    id: &str,
+) -> Result<NamedFile, Status> {
+    let downloaded_path = state
+        .proxy
+        .get_video(source, id, &config.media_format)
+        .await
+        .map_err(|e| match e {
+            PodcastError::NotFound => Status::NotFound,
+            _ => Status::InternalServerError,
+        })?;
 
     let file = NamedFile::open(downloaded_path)
         .await
@@ -78,15 +106,26 @@ async fn not_found() -> String {
 async fn main() -> Result<()> {
     dotenv().ok();
 
-    let cache = Cache::new()?;
+    let rocket = rocket::build();
 
-    let state = AppState {
-        proxy: PodcastProxy { cache },
-    };
+    let mut config: AppConfig = rocket.figment().extract()?;
 
-    let rocket = rocket::build();
+    #[cfg(feature = "downloader")]
+    {
+        let data_dir = std::env::temp_dir().join("yt-cast-bin");
+        if let Err(e) = downloader::ensure_yt_dlp(&mut config.ytdlp, &data_dir).await {
+            log::warn!("Failed to ensure yt-dlp is available: {}", e);
+        }
+    }
+
+    let cache = Cache::new(&config.cache)?;
 
-    let config: AppConfig = rocket.figment().extract()?;
+    let state = AppState {
+        proxy: PodcastProxy {
+            cache,
+            ytdlp_config: config.ytdlp.clone(),
+        },
+    };
 
     rocket
         .mount("/", routes![get_feed, get_media])
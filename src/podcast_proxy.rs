@@ -1,63 +1,74 @@
 use std::collections::HashMap;
 
 use anyhow::{anyhow, Context, Result};
-use async_std::fs;
 use async_std::path::PathBuf;
 use chrono::{Duration, NaiveDate, TimeZone, Utc};
 use rss::extension::itunes::{ITunesChannelExtensionBuilder, ITunesItemExtensionBuilder};
 use rss::{ChannelBuilder, EnclosureBuilder, GuidBuilder, ImageBuilder, ItemBuilder};
 use thiserror::Error;
 
+use super::backend::{Backend, BackendError, SourceBackend};
 use super::cache::Cache;
-use super::ytdl;
-use super::ytdl::YtDlError;
+use super::ytdl::{Channel, MediaFormat, Video, YtdlpConfig};
 
 #[derive(Error, Debug)]
 pub enum PodcastError {
     #[error("Not found")]
     NotFound,
-    #[error("Youtube error")]
-    YoutubeError(#[from] ytdl::YtDlError),
-    #[error(transparent)]
-    IoError(#[from] std::io::Error),
+    #[error("Backend error")]
+    BackendError(#[from] BackendError),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+fn map_backend_error(e: BackendError) -> PodcastError {
+    match e {
+        BackendError::NotFound => PodcastError::NotFound,
+        other => PodcastError::BackendError(other),
+    }
+}
+
 pub struct PodcastProxy {
     pub cache: Cache,
+    pub ytdlp_config: YtdlpConfig,
 }
 
 impl PodcastProxy {
     pub async fn get_feed(
         &self,
         media_base_url: &str,
-        channel_name: &str,
+        source: &str,
+        item_id: &str,
         delay_days: u32,
+        media_format: &MediaFormat,
     ) -> Result<String, PodcastError> {
-        let yt = ytdl::YtDl::new(&self.cache);
+        let mut ytdlp_config = self.ytdlp_config.clone();
 
-        let channel = yt.get_channel_info(channel_name).await.map_err(|e| {
-            println!("{:?}", e);
-            match e {
-                YtDlError::ItemNotFound => PodcastError::NotFound,
-                _ => PodcastError::YoutubeError(e),
-            }
-        })?;
-        let vids = yt.get_channel_videos(&channel, None).await?;
+        let channel = self
+            .resolve_feed_with_retry(source, item_id, &mut ytdlp_config)
+            .await?;
+        let vids = self
+            .list_items_with_retry(source, &channel, media_format, &mut ytdlp_config)
+            .await?;
 
         const ARBITRARY_SIZE: u64 = 1_073_741_824;
 
         let base_url = media_base_url.to_string();
+        let mime_type = media_format.mime_type();
 
         let mut oldest_date = Utc::now();
 
         let mut rss_items = vec![];
         for vid in vids {
+            let length = match self.cached_media_size(&vid.id, media_format).await? {
+                Some(size) => size,
+                None => vid.filesize.unwrap_or(ARBITRARY_SIZE),
+            };
+
             let enclosure = EnclosureBuilder::default()
                 .url(base_url.clone() + &vid.id)
-                .length(ARBITRARY_SIZE.to_string())
-                .mime_type("video/mp4".to_owned())
+                .length(length.to_string())
+                .mime_type(mime_type.to_owned())
                 .build()
                 .map_err(|e| anyhow!(e))?;
 
@@ -152,26 +163,160 @@ impl PodcastProxy {
         Ok(rss_channel.to_string())
     }
 
-    pub async fn get_video(&self, video_id: &str) -> Result<PathBuf, PodcastError> {
-        let out_path = self
+    pub async fn get_video(
+        &self,
+        source: &str,
+        video_id: &str,
+        media_format: &MediaFormat,
+    ) -> Result<PathBuf, PodcastError> {
+        let mut ytdlp_config = self.ytdlp_config.clone();
+        self.fetch_media_with_retry(source, video_id, media_format, &mut ytdlp_config)
+            .await
+    }
+
+    /// The real byte size of a previously-downloaded file, cached by the
+    /// backend on download, so feed renders don't have to rely on yt-dlp's
+    /// up-front size estimate.
+    async fn cached_media_size(
+        &self,
+        video_id: &str,
+        media_format: &MediaFormat,
+    ) -> Result<Option<u64>, PodcastError> {
+        let cached = self
             .cache
-            .get_path(vec!["media", video_id], Some("mp4"))
+            .read(&["size", media_format.extension(), video_id])
             .await?;
 
-        if out_path.exists().await && fs::metadata(&out_path).await?.len() == 0 {
-            fs::remove_file(&out_path).await?;
+        Ok(cached.and_then(|s| s.trim().parse().ok()))
+    }
+
+    /// Resolves a feed, downloading a fresh `yt-dlp` once and retrying if
+    /// the configured executable can't be found, when the `downloader`
+    /// feature is enabled.
+    #[cfg(feature = "downloader")]
+    async fn resolve_feed_with_retry(
+        &self,
+        source: &str,
+        item_id: &str,
+        ytdlp_config: &mut YtdlpConfig,
+    ) -> Result<Channel, PodcastError> {
+        match SourceBackend::for_source(source, &self.cache, ytdlp_config)
+            .resolve_feed(item_id)
+            .await
+        {
+            Err(BackendError::YtDlpNotFound) => {
+                self.redownload_yt_dlp(ytdlp_config).await?;
+                SourceBackend::for_source(source, &self.cache, ytdlp_config)
+                    .resolve_feed(item_id)
+                    .await
+                    .map_err(map_backend_error)
+            }
+            Err(e) => Err(map_backend_error(e)),
+            Ok(channel) => Ok(channel),
+        }
+    }
+
+    #[cfg(not(feature = "downloader"))]
+    async fn resolve_feed_with_retry(
+        &self,
+        source: &str,
+        item_id: &str,
+        ytdlp_config: &YtdlpConfig,
+    ) -> Result<Channel, PodcastError> {
+        SourceBackend::for_source(source, &self.cache, ytdlp_config)
+            .resolve_feed(item_id)
+            .await
+            .map_err(map_backend_error)
+    }
+
+    /// As `resolve_feed_with_retry`, for listing a feed's items.
+    #[cfg(feature = "downloader")]
+    async fn list_items_with_retry(
+        &self,
+        source: &str,
+        channel: &Channel,
+        media_format: &MediaFormat,
+        ytdlp_config: &mut YtdlpConfig,
+    ) -> Result<Vec<Video>, PodcastError> {
+        match SourceBackend::for_source(source, &self.cache, ytdlp_config)
+            .list_items(channel, None, media_format)
+            .await
+        {
+            Err(BackendError::YtDlpNotFound) => {
+                self.redownload_yt_dlp(ytdlp_config).await?;
+                SourceBackend::for_source(source, &self.cache, ytdlp_config)
+                    .list_items(channel, None, media_format)
+                    .await
+                    .map_err(map_backend_error)
+            }
+            Err(e) => Err(map_backend_error(e)),
+            Ok(vids) => Ok(vids),
         }
+    }
 
-        if !out_path.exists().await {
-            let yt = ytdl::YtDl::new(&self.cache);
-            yt.download_video(video_id, &out_path)
-                .await
-                .map_err(|e| match e {
-                    YtDlError::ItemNotFound => PodcastError::NotFound,
-                    _ => PodcastError::YoutubeError(e),
-                })?;
+    #[cfg(not(feature = "downloader"))]
+    async fn list_items_with_retry(
+        &self,
+        source: &str,
+        channel: &Channel,
+        media_format: &MediaFormat,
+        ytdlp_config: &YtdlpConfig,
+    ) -> Result<Vec<Video>, PodcastError> {
+        SourceBackend::for_source(source, &self.cache, ytdlp_config)
+            .list_items(channel, None, media_format)
+            .await
+            .map_err(map_backend_error)
+    }
+
+    /// As `resolve_feed_with_retry`, for fetching a single item's media.
+    #[cfg(feature = "downloader")]
+    async fn fetch_media_with_retry(
+        &self,
+        source: &str,
+        video_id: &str,
+        media_format: &MediaFormat,
+        ytdlp_config: &mut YtdlpConfig,
+    ) -> Result<PathBuf, PodcastError> {
+        match SourceBackend::for_source(source, &self.cache, ytdlp_config)
+            .fetch_media(video_id, media_format)
+            .await
+        {
+            Err(BackendError::YtDlpNotFound) => {
+                self.redownload_yt_dlp(ytdlp_config).await?;
+                SourceBackend::for_source(source, &self.cache, ytdlp_config)
+                    .fetch_media(video_id, media_format)
+                    .await
+                    .map_err(map_backend_error)
+            }
+            Err(e) => Err(map_backend_error(e)),
+            Ok(path) => Ok(path),
         }
+    }
+
+    #[cfg(not(feature = "downloader"))]
+    async fn fetch_media_with_retry(
+        &self,
+        source: &str,
+        video_id: &str,
+        media_format: &MediaFormat,
+        ytdlp_config: &YtdlpConfig,
+    ) -> Result<PathBuf, PodcastError> {
+        SourceBackend::for_source(source, &self.cache, ytdlp_config)
+            .fetch_media(video_id, media_format)
+            .await
+            .map_err(map_backend_error)
+    }
 
-        Ok(out_path)
+    /// Downloads a fresh `yt-dlp` binary into the app's data directory and
+    /// points `ytdlp_config` at it, for the `YtDlpNotFound` retry path.
+    #[cfg(feature = "downloader")]
+    async fn redownload_yt_dlp(&self, ytdlp_config: &mut YtdlpConfig) -> Result<(), PodcastError> {
+        // The failed backend run already found this executable missing;
+        // force a fresh download rather than re-probing it.
+        ytdlp_config.auto_update = true;
+        let data_dir = std::env::temp_dir().join("yt-cast-bin");
+        super::downloader::ensure_yt_dlp(ytdlp_config, &data_dir)
+            .await
+            .map_err(PodcastError::Other)
     }
 }
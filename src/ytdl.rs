@@ -3,8 +3,6 @@ use std::str;
 
 use anyhow::{anyhow, Context, Result};
 use async_process::{Command, Output};
-use async_std::fs;
-use async_std::fs::read_to_string;
 use async_std::path::PathBuf;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -12,7 +10,7 @@ use serde_aux::serde_introspection::serde_introspect;
 use thiserror::Error;
 use urlencoding::encode;
 
-use super::cache::Cache;
+use super::cache::{Cache, CacheConfig};
 
 #[derive(Error, Debug)]
 pub enum YtDlError {
@@ -33,14 +31,19 @@ pub struct Thumbnail {
     pub height: u16,
 }
 
+/// Most fields default since non-YouTube extractors won't all populate them.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Channel {
+    #[serde(default)]
     pub channel: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(default)]
     pub thumbnails: Vec<Thumbnail>,
     pub webpage_url: String,
     #[serde(default)]
     pub videos_url: String,
+    #[serde(default)]
     pub epoch: u64,
 }
 
@@ -53,19 +56,86 @@ pub struct Video {
     pub uploader: String,
     #[serde(rename = "duration_string")]
     pub duration: String,
+    #[serde(default)]
+    pub filesize: Option<u64>,
+    #[serde(default)]
+    pub webpage_url: String,
+}
+
+/// The media format to request from yt-dlp and advertise in the feed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaFormat {
+    VideoMp4 { max_height: u32 },
+    AudioM4a,
+    AudioMp3,
+}
+
+impl MediaFormat {
+    /// The file extension used for the cached/served media file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MediaFormat::VideoMp4 { .. } => "mp4",
+            MediaFormat::AudioM4a => "m4a",
+            MediaFormat::AudioMp3 => "mp3",
+        }
+    }
+
+    /// The MIME type advertised on the RSS enclosure.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            MediaFormat::VideoMp4 { .. } => "video/mp4",
+            MediaFormat::AudioM4a => "audio/mp4",
+            MediaFormat::AudioMp3 => "audio/mpeg",
+        }
+    }
+}
+
+/// Operator-tunable settings for how the `yt-dlp` binary is invoked, e.g. to
+/// point at a custom build, inject global flags (rate limiting, proxies), or
+/// authenticate via a cookies file for age-restricted/members-only content.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct YtdlpConfig {
+    #[serde(default = "YtdlpConfig::default_executable_path")]
+    pub executable_path: String,
+    #[serde(default)]
+    pub working_directory: Option<std::path::PathBuf>,
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    #[serde(default)]
+    pub cookies_file: Option<std::path::PathBuf>,
+    /// Whether the `downloader` feature should fetch a fresh `yt-dlp` binary
+    /// on startup even if `executable_path` already resolves to one.
+    #[serde(default)]
+    pub auto_update: bool,
+}
+
+impl YtdlpConfig {
+    fn default_executable_path() -> String {
+        "yt-dlp".to_string()
+    }
+}
+
+impl Default for YtdlpConfig {
+    fn default() -> Self {
+        Self {
+            executable_path: YtdlpConfig::default_executable_path(),
+            working_directory: None,
+            extra_args: Vec::new(),
+            cookies_file: None,
+            auto_update: false,
+        }
+    }
 }
 
 pub struct YtDl<'a> {
-    pub ytdlp_path: String,
+    pub config: &'a YtdlpConfig,
     pub cache: &'a Cache,
 }
 
 impl<'a> YtDl<'a> {
-    pub fn new(cache: &'a Cache) -> Self {
-        Self {
-            ytdlp_path: "yt-dlp".to_string(),
-            cache,
-        }
+    pub fn new(cache: &'a Cache, config: &'a YtdlpConfig) -> Self {
+        Self { config, cache }
     }
 
     fn get_channel_url(channel_name: &str, page: &str) -> String {
@@ -84,8 +154,67 @@ impl<'a> YtDl<'a> {
         )
     }
 
+    fn get_handle_url(handle: &str, page: &str) -> String {
+        format!(
+            "https://www.youtube.com/@{}/{}",
+            encode(handle.trim_start_matches('@')),
+            encode(page)
+        )
+    }
+
+    fn get_channel_id_url(channel_id: &str, page: &str) -> String {
+        format!(
+            "https://www.youtube.com/channel/{}/{}",
+            encode(channel_id),
+            encode(page)
+        )
+    }
+
+    fn get_playlist_url(list_id: &str) -> String {
+        format!("https://www.youtube.com/playlist?list={}", encode(list_id))
+    }
+
+    /// Candidate (about page, videos page) URLs to try in order when
+    /// resolving a channel, since YouTube supports several identifier
+    /// schemes (`@handle`, `channel/UC…`, legacy `/c/` and `/user/` names)
+    /// and a bare identifier could also be a playlist id.
+    fn candidate_urls(channel_name: &str) -> Vec<(String, String)> {
+        let playlist_url = YtDl::get_playlist_url(channel_name);
+        vec![
+            (
+                YtDl::get_handle_url(channel_name, "about"),
+                YtDl::get_handle_url(channel_name, "videos"),
+            ),
+            (
+                YtDl::get_channel_id_url(channel_name, "about"),
+                YtDl::get_channel_id_url(channel_name, "videos"),
+            ),
+            (
+                YtDl::get_channel_url(channel_name, "about"),
+                YtDl::get_channel_url(channel_name, "videos"),
+            ),
+            (
+                YtDl::get_user_url(channel_name, "about"),
+                YtDl::get_user_url(channel_name, "videos"),
+            ),
+            (playlist_url.clone(), playlist_url),
+        ]
+    }
+
     pub async fn run(&self, args: &[&str]) -> Result<Output, YtDlError> {
-        let output = Command::new(&self.ytdlp_path)
+        let mut command = Command::new(&self.config.executable_path);
+
+        if let Some(working_directory) = &self.config.working_directory {
+            command.current_dir(working_directory);
+        }
+
+        command.args(&self.config.extra_args);
+
+        if let Some(cookies_file) = &self.config.cookies_file {
+            command.arg("--cookies").arg(cookies_file);
+        }
+
+        let output = command
             .args(args)
             .output()
             .await
@@ -128,30 +257,45 @@ impl<'a> YtDl<'a> {
     pub async fn get_channel_info(&self, channel_name: &str) -> Result<Channel, YtDlError> {
         log::debug!("get_channel_info");
 
-        let channel_about_url = YtDl::get_channel_url(channel_name, "about");
-        let user_about_url = YtDl::get_user_url(channel_name, "about");
-
-        let mut vids_url = YtDl::get_channel_url(channel_name, "videos");
-        let output_result = self.run_get_channel_info(&channel_about_url).await;
-        let output = match output_result {
-            Ok(o) => Ok(o),
-            Err(e) => match e {
-                // Try user url if channel url didn't work
-                YtDlError::ItemNotFound => {
-                    vids_url = YtDl::get_user_url(channel_name, "videos");
-                    self.run_get_channel_info(&user_about_url).await
+        // Try each URL form in turn (handle, channel id, legacy /c/ and
+        // /user/ names, then as a playlist id) and use the first that
+        // yt-dlp resolves without a 404.
+        for (about_url, videos_url) in YtDl::candidate_urls(channel_name) {
+            match self.run_get_channel_info(&about_url).await {
+                Ok(output) => {
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let mut channel: Channel =
+                        serde_json::from_str(&stdout).context("failed to parse ytdl output")?;
+                    channel.videos_url = videos_url;
+
+                    return Ok(channel);
                 }
-                _ => {
+                Err(YtDlError::ItemNotFound) => continue,
+                Err(e) => {
                     log::error!("Bad output_result: {}", e);
-                    Err(e)
+                    return Err(e);
                 }
-            },
-        }?;
+            }
+        }
+
+        Err(YtDlError::ItemNotFound)
+    }
+
+    /// Resolves feed metadata for an arbitrary yt-dlp extractor URL, rather
+    /// than guessing at YouTube's channel/user page layout. Used for sources
+    /// other than YouTube, where the given URL doubles as the videos listing.
+    pub async fn get_extractor_info(&self, url: &str) -> Result<Channel, YtDlError> {
+        log::debug!("get_extractor_info({})", url);
+
+        let output = self
+            .run(&["-J", "--flat-playlist", url])
+            .await
+            .map_err(|e| YtDl::map_not_found(e, "HTTPError 404"))?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         let mut channel: Channel =
             serde_json::from_str(&stdout).context("failed to parse ytdl output")?;
-        channel.videos_url = vids_url;
+        channel.videos_url = url.to_string();
 
         Ok(channel)
     }
@@ -160,48 +304,64 @@ impl<'a> YtDl<'a> {
         &self,
         channel_info: &Channel,
         limit: Option<i64>,
+        format: &MediaFormat,
     ) -> Result<Vec<Video>, YtDlError> {
         log::debug!("get_channel_videos");
 
         let limit = limit.unwrap_or(5);
 
-        let cache_path = self
-            .cache
-            .get_path(vec!["playlist", &channel_info.channel], None)
-            .await?;
-
-        let cached = read_to_string(&cache_path)
-            .await
-            .context("cache read failed")?;
-        let output = if cached.is_empty() {
-            // Prepare a template for ytdl requesting just the data we need (it's faster that way)
+        // `channel` defaults to "" for generic extractors that don't expose
+        // one, so key on `videos_url` (always unique) rather than the name.
+        let cache_key = ["playlist", &channel_info.videos_url];
+        let cached = self.cache.read(&cache_key).await?;
+        let output = if let Some(cached) = cached {
+            cached
+        } else {
+            // Prepare a template for ytdl requesting just the data we need (it's faster that way).
+            // `filesize` is unreliable up front, so also fall back to the approximate size.
             let field_template = String::from("{")
                 + &serde_introspect::<Video>()
                     .iter() // struct field names
-                    .map(|f| format!("\"{}\":%({})j", f, f))
+                    .map(|f| {
+                        if *f == "filesize" {
+                            format!("\"{}\":%(filesize,filesize_approx)j", f)
+                        } else {
+                            format!("\"{}\":%({})j", f, f)
+                        }
+                    })
                     .join(",")
                 + "}";
 
-            let out = self
-                .run(&[
-                    "-S",
-                    "ext",
-                    "--print",
-                    &field_template,
-                    "--playlist-end",
-                    &limit.to_string(),
-                    &channel_info.videos_url,
-                ])
-                .await?;
-            let out_str = String::from_utf8_lossy(&out.stdout).into();
-
-            if let Err(e) = fs::write(&cache_path, &out_str).await {
+            // Mirror the format selection used when actually downloading, so the
+            // reported filesize matches what will be served.
+            let format_sort = match format {
+                MediaFormat::VideoMp4 { max_height } => Some(format!("ext,height:{}", max_height)),
+                MediaFormat::AudioM4a | MediaFormat::AudioMp3 => None,
+            };
+            let mut args = vec!["--print", &field_template];
+            match &format_sort {
+                Some(sort) => {
+                    args.push("-S");
+                    args.push(sort);
+                }
+                None => {
+                    args.push("-f");
+                    args.push("bestaudio");
+                }
+            }
+            args.push("--playlist-end");
+            let limit_str = limit.to_string();
+            args.push(&limit_str);
+            args.push(&channel_info.videos_url);
+
+            let out = self.run(&args).await?;
+            let out_str: String = String::from_utf8_lossy(&out.stdout).into();
+
+            if let Err(e) = self.cache.write(&cache_key, &out_str).await {
                 log::error!("Failed to save cache: {}", e);
             }
 
             out_str
-        } else {
-            cached
         };
 
         let vids = output
@@ -212,20 +372,59 @@ impl<'a> YtDl<'a> {
         Ok(vids)
     }
 
-    pub async fn download_video(&self, id: &str, output: &PathBuf) -> Result<(), YtDlError> {
-        let vid_url = format!("https://www.youtube.com/watch?v={}", encode(id));
-
-        self.run(&[
-            "--sponsorblock-remove",
-            "all",
-            "-S",
-            "ext,height:720",
-            "-o",
-            output.to_str().ok_or_else(|| anyhow!("bad output path"))?,
-            &vid_url,
-        ])
-        .await
-        .map_err(|e| YtDl::map_not_found(e, "Video unavailable"))?;
+    /// Downloads the media at `url` (the caller resolves whatever id/url
+    /// scheme its source uses) into `output` in the given format.
+    pub async fn download_video(
+        &self,
+        url: &str,
+        output: &PathBuf,
+        format: &MediaFormat,
+    ) -> Result<(), YtDlError> {
+        let output_str = output.to_str().ok_or_else(|| anyhow!("bad output path"))?;
+        let height_sort = match format {
+            MediaFormat::VideoMp4 { max_height } => format!("ext,height:{}", max_height),
+            _ => String::new(),
+        };
+
+        let args: Vec<&str> = match format {
+            MediaFormat::VideoMp4 { .. } => vec![
+                "--sponsorblock-remove",
+                "all",
+                "-S",
+                &height_sort,
+                "-o",
+                output_str,
+                url,
+            ],
+            MediaFormat::AudioM4a => vec![
+                "--sponsorblock-remove",
+                "all",
+                "-f",
+                "bestaudio",
+                "-x",
+                "--audio-format",
+                "m4a",
+                "-o",
+                output_str,
+                url,
+            ],
+            MediaFormat::AudioMp3 => vec![
+                "--sponsorblock-remove",
+                "all",
+                "-f",
+                "bestaudio",
+                "-x",
+                "--audio-format",
+                "mp3",
+                "-o",
+                output_str,
+                url,
+            ],
+        };
+
+        self.run(&args)
+            .await
+            .map_err(|e| YtDl::map_not_found(e, "Video unavailable"))?;
 
         Ok(())
     }
@@ -235,12 +434,31 @@ impl<'a> YtDl<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_media_format_audio_mime_types() {
+        assert_eq!(MediaFormat::AudioM4a.mime_type(), "audio/mp4");
+        assert_eq!(MediaFormat::AudioM4a.extension(), "m4a");
+        assert_eq!(MediaFormat::AudioMp3.mime_type(), "audio/mpeg");
+        assert_eq!(MediaFormat::AudioMp3.extension(), "mp3");
+        assert_eq!(
+            MediaFormat::VideoMp4 { max_height: 720 }.mime_type(),
+            "video/mp4"
+        );
+        assert_eq!(
+            MediaFormat::VideoMp4 { max_height: 720 }.extension(),
+            "mp4"
+        );
+    }
+
     #[tokio::test]
     async fn test_get_channel_videos() -> Result<()> {
-        let cache = Cache::new()?;
-        let yt = YtDl::new(&cache);
+        let cache = Cache::new(&CacheConfig::default())?;
+        let config = YtdlpConfig::default();
+        let yt = YtDl::new(&cache, &config);
         let info = yt.get_channel_info("techmoan").await?;
-        let vids = yt.get_channel_videos(&info, None).await?;
+        let vids = yt
+            .get_channel_videos(&info, None, &MediaFormat::VideoMp4 { max_height: 720 })
+            .await?;
 
         assert!(!vids.is_empty());
 
@@ -249,8 +467,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_channel_info() -> Result<()> {
-        let cache = Cache::new()?;
-        let yt = YtDl::new(&cache);
+        let cache = Cache::new(&CacheConfig::default())?;
+        let config = YtdlpConfig::default();
+        let yt = YtDl::new(&cache, &config);
         let info = yt.get_channel_info("techmoan").await?;
 
         assert_eq!(info.channel, "Techmoan");
@@ -260,8 +479,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_channel_info_not_found() -> Result<()> {
-        let cache = Cache::new()?;
-        let yt = YtDl::new(&cache);
+        let cache = Cache::new(&CacheConfig::default())?;
+        let config = YtdlpConfig::default();
+        let yt = YtDl::new(&cache, &config);
 
         match yt
             .get_channel_info("thischannelhopefullydoesnotexist")